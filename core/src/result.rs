@@ -1,8 +1,9 @@
-use crate::{qjs, Ctx, Object, StdResult, StdString, Type};
+use crate::{qjs, Ctx, Object, Runtime, StdResult, StdString, Type};
 
 use std::{
+    collections::HashMap,
     error::Error as StdError,
-    ffi::{CString, FromBytesWithNulError, NulError},
+    ffi::{CStr, CString, FromBytesWithNulError, NulError},
     fmt::{Display, Formatter, Result as FmtResult},
     io::Error as IoError,
     ops::Range,
@@ -10,6 +11,7 @@ use std::{
     panic::UnwindSafe,
     str::{FromStr, Utf8Error},
     string::FromUtf8Error,
+    sync::Arc,
 };
 
 /// Result type used throught the library.
@@ -35,11 +37,13 @@ pub enum Error {
     /// An io error
     Io(IoError),
     /// An exception raised by quickjs itself.
-    /// The actual javascript value can be retrieved by calling `Ctx::catch`.
+    /// The actual javascript value can still be retrieved by calling `Ctx::catch`, but the
+    /// `name`, `message`, and `stack` of the exception are captured here as well so the error
+    /// is self-describing without a second round trip into the context.
     ///
     /// When returned from a callback the javascript will continue to unwind with the current
     /// error.
-    Exception,
+    Exception(Option<Box<JsException>>),
     /// Error converting from javascript to a rust type.
     FromJs {
         from: &'static str,
@@ -75,6 +79,254 @@ pub enum Error {
     /// An error from quickjs from which the specifics are unknown.
     /// Should eventually be removed as development progresses.
     Unknown,
+    /// An error to be thrown as a specific JS error class, either one of the standard
+    /// builtins or a user-defined class looked up on the global object by name.
+    Throw {
+        class: ErrorClass,
+        message: StdString,
+    },
+    /// The JSON passed to `Ctx::register_source_map` was not a valid source map.
+    InvalidSourceMap(StdString),
+    /// A Rust error raised from a callback, carried through the JS boundary without being
+    /// flattened to a string. See [`Error::wrap`].
+    Wrapped(Arc<dyn StdError + Send + Sync + 'static>),
+}
+
+/// The class of JS error [`Error::Throw`] should construct when thrown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorClass {
+    /// A plain `Error`.
+    Error,
+    /// A `TypeError`.
+    Type,
+    /// A `RangeError`.
+    Range,
+    /// A `ReferenceError`.
+    Reference,
+    /// A `SyntaxError`.
+    Syntax,
+    /// A `URIError`.
+    Uri,
+    /// An `EvalError`.
+    Eval,
+    /// A user-defined error class, looked up as a constructor on the global object by name.
+    Custom(StdString),
+}
+
+/// The captured contents of a JS exception, read off the thrown value at the moment it was
+/// caught so it remains available after the originating `Ctx` has moved on.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct JsException {
+    /// The exception's `name` property, e.g. `"TypeError"`.
+    pub name: StdString,
+    /// The exception's `message` property.
+    pub message: StdString,
+    /// The exception's `stack` property, if it has one.
+    pub stack: Option<StdString>,
+    /// The source file of the topmost stack frame, if it could be determined.
+    pub file: Option<StdString>,
+    /// The source line of the topmost stack frame, if it could be determined.
+    pub line: Option<u32>,
+}
+
+impl Display for JsException {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.name.fmt(f)?;
+        if !self.message.is_empty() {
+            ": ".fmt(f)?;
+            self.message.fmt(f)?;
+        }
+        if let Some(stack) = self.stack.as_ref() {
+            "\n".fmt(f)?;
+            stack.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A parsed source map, used to rewrite generated stack-frame positions in a captured
+/// [`JsException`] back to the original source for transpiled or bundled code.
+///
+/// Register one per script URL with [`Ctx::register_source_map`].
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    sources: Vec<StdString>,
+    /// Segments grouped by generated line, sorted by generated column.
+    lines: Vec<Vec<Segment>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    gen_col: u32,
+    src_idx: u32,
+    src_line: u32,
+    src_col: u32,
+}
+
+impl SourceMap {
+    /// Parse a source map in the standard JSON format (`version`, `sources`, `names`,
+    /// `mappings`). Returns `None` if `json` has no `mappings` string.
+    pub fn parse(json: &str) -> Option<Self> {
+        let sources = json_string_array(json, "sources").unwrap_or_default();
+        let mappings = json_string_field(json, "mappings")?;
+        Some(SourceMap {
+            sources,
+            lines: parse_mappings(&mappings),
+        })
+    }
+
+    /// Resolve a 0-based generated `(line, col)` to `source:line:col`, using the segment
+    /// on that line with the greatest `gen_col <= col`.
+    fn resolve(&self, line: u32, col: u32) -> Option<(&str, u32, u32)> {
+        let segments = self.lines.get(line as usize)?;
+        let index = match segments.binary_search_by_key(&col, |s| s.gen_col) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let segment = &segments[index];
+        let source = self.sources.get(segment.src_idx as usize)?;
+        Some((source, segment.src_line, segment.src_col))
+    }
+
+    /// Resolve a 0-based generated line with no column information, using the first segment
+    /// recorded for that line. Used as a fallback for stack frames that only carry a line.
+    fn resolve_line(&self, line: u32) -> Option<(&str, u32, u32)> {
+        let segment = self.lines.get(line as usize)?.first()?;
+        let source = self.sources.get(segment.src_idx as usize)?;
+        Some((source, segment.src_line, segment.src_col))
+    }
+}
+
+/// Decode a `;`-separated `mappings` string into per-line segments, maintaining the running
+/// accumulators the spec defines: `gen_col` resets every line, the rest persist across lines.
+fn parse_mappings(mappings: &str) -> Vec<Vec<Segment>> {
+    let (mut src_idx, mut src_line, mut src_col) = (0i64, 0i64, 0i64);
+    mappings
+        .split(';')
+        .map(|line| {
+            let mut gen_col = 0i64;
+            let mut segments = Vec::new();
+            for field in line.split(',') {
+                if field.is_empty() {
+                    continue;
+                }
+                let values = base64_vlq_decode(field);
+                // A 1-field segment (generated column only) has nothing to map to, but its
+                // column delta still advances the accumulator for the rest of the line.
+                if values.len() < 4 {
+                    if let Some(delta) = values.first() {
+                        gen_col += delta;
+                    }
+                    continue;
+                }
+                gen_col += values[0];
+                src_idx += values[1];
+                src_line += values[2];
+                src_col += values[3];
+                segments.push(Segment {
+                    gen_col: gen_col.max(0) as u32,
+                    src_idx: src_idx.max(0) as u32,
+                    src_line: src_line.max(0) as u32,
+                    src_col: src_col.max(0) as u32,
+                });
+            }
+            segments
+        })
+        .collect()
+}
+
+/// Decode one Base64-VLQ-encoded mapping segment into its delta-encoded fields.
+fn base64_vlq_decode(segment: &str) -> Vec<i64> {
+    const BASE64_CHARS: &[u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut values = Vec::new();
+    let mut shift = 0u32;
+    let mut value = 0i64;
+    for byte in segment.bytes() {
+        let Some(digit) = BASE64_CHARS.iter().position(|&b| b == byte) else {
+            continue;
+        };
+        value += ((digit as i64) & 0x1f) << shift;
+        if digit & 0x20 != 0 {
+            shift += 5;
+        } else {
+            let decoded = value >> 1;
+            values.push(if value & 1 != 0 { -decoded } else { decoded });
+            shift = 0;
+            value = 0;
+        }
+    }
+    values
+}
+
+/// Extract a top-level JSON string field's raw contents, e.g. `"mappings": "..."`.
+///
+/// This is a minimal, dependency-free reader for the handful of fields this subsystem
+/// needs; it does not handle escape sequences within the string.
+fn json_string_field(json: &str, key: &str) -> Option<StdString> {
+    let needle = format!("\"{key}\"");
+    let after_key = json[json.find(&needle)? + needle.len()..].trim_start();
+    let rest = after_key.strip_prefix(':')?.trim_start().strip_prefix('"')?;
+    let end = rest.find('"')?;
+    Some(rest[..end].to_string())
+}
+
+/// Extract a top-level JSON array of strings, e.g. `"sources": ["a.ts", "b.ts"]`.
+fn json_string_array(json: &str, key: &str) -> Option<Vec<StdString>> {
+    let needle = format!("\"{key}\"");
+    let after_key = json[json.find(&needle)? + needle.len()..].trim_start();
+    let rest = after_key.strip_prefix(':')?.trim_start().strip_prefix('[')?;
+    let end = rest.find(']')?;
+    Some(
+        rest[..end]
+            .split(',')
+            .map(|s| s.trim().trim_matches('"'))
+            .filter(|s| !s.is_empty())
+            .map(StdString::from)
+            .collect(),
+    )
+}
+
+/// Signature of a user-installable hook, modeled on Deno's `GetErrorClassFn`, that maps an
+/// [`Error`] to the name of the JS error class [`Error::throw`] should construct for it.
+/// Return `None` to fall back to the built-in mapping.
+pub type GetErrorClassFn = dyn Fn(&Error) -> Option<&'static str> + Send + Sync + 'static;
+
+impl Runtime {
+    /// Install a hook which `Error::throw` consults before falling back to its built-in
+    /// class mapping. This lets an embedder route, say, their own wrapped errors to a
+    /// domain-specific class, or change what class a given variant maps to, without
+    /// patching the crate.
+    ///
+    /// Requires the runtime opaque data to carry an `error_class_fn: Option<Box<GetErrorClassFn>>`
+    /// field, defaulted to `None`; that struct lives outside `result.rs` and isn't part of this
+    /// change.
+    pub fn set_error_class_fn<F>(&self, f: F)
+    where
+        F: Fn(&Error) -> Option<&'static str> + Send + Sync + 'static,
+    {
+        self.get_opaque().error_class_fn = Some(Box::new(f));
+    }
+}
+
+impl ErrorClass {
+    /// The name of this class' constructor on the global object.
+    pub fn name(&self) -> &str {
+        match self {
+            ErrorClass::Error => "Error",
+            ErrorClass::Type => "TypeError",
+            ErrorClass::Range => "RangeError",
+            ErrorClass::Reference => "ReferenceError",
+            ErrorClass::Syntax => "SyntaxError",
+            ErrorClass::Uri => "URIError",
+            ErrorClass::Eval => "EvalError",
+            ErrorClass::Custom(name) => name,
+        }
+    }
 }
 
 impl Error {
@@ -142,7 +394,16 @@ impl Error {
 
     /// Returns whether the error is a quickjs generated exception.
     pub fn is_exception(&self) -> bool {
-        matches!(self, Error::Exception)
+        matches!(self, Error::Exception(_))
+    }
+
+    /// Returns the captured JS exception details, if this error is an [`Error::Exception`]
+    /// for which they could be read.
+    pub fn as_exception(&self) -> Option<&JsException> {
+        match self {
+            Error::Exception(e) => e.as_deref(),
+            _ => None,
+        }
     }
 
     /// Create from JS conversion error
@@ -212,6 +473,126 @@ impl Error {
         matches!(self, Self::NumArgs { .. })
     }
 
+    /// Create an error which throws a plain `Error` with the given message.
+    pub fn new_error<M>(msg: M) -> Self
+    where
+        StdString: From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Error,
+            message: msg.into(),
+        }
+    }
+
+    /// Create an error which throws a `TypeError` with the given message.
+    pub fn new_type<M>(msg: M) -> Self
+    where
+        StdString: From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Type,
+            message: msg.into(),
+        }
+    }
+
+    /// Create an error which throws a `RangeError` with the given message.
+    pub fn new_range<M>(msg: M) -> Self
+    where
+        StdString: From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Range,
+            message: msg.into(),
+        }
+    }
+
+    /// Create an error which throws a `ReferenceError` with the given message.
+    pub fn new_reference<M>(msg: M) -> Self
+    where
+        StdString: From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Reference,
+            message: msg.into(),
+        }
+    }
+
+    /// Create an error which throws a `SyntaxError` with the given message.
+    pub fn new_syntax<M>(msg: M) -> Self
+    where
+        StdString: From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Syntax,
+            message: msg.into(),
+        }
+    }
+
+    /// Create an error which throws a `URIError` with the given message.
+    pub fn new_uri<M>(msg: M) -> Self
+    where
+        StdString: From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Uri,
+            message: msg.into(),
+        }
+    }
+
+    /// Create an error which throws an `EvalError` with the given message.
+    pub fn new_eval<M>(msg: M) -> Self
+    where
+        StdString: From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Eval,
+            message: msg.into(),
+        }
+    }
+
+    /// Create an error which throws a user-defined error class, looked up as a constructor
+    /// on the global object by name.
+    pub fn new_custom<N, M>(class: N, msg: M) -> Self
+    where
+        StdString: From<N> + From<M>,
+    {
+        Self::Throw {
+            class: ErrorClass::Custom(class.into()),
+            message: msg.into(),
+        }
+    }
+
+    /// Returns whether the error is a structured [`Error::Throw`] error.
+    pub fn is_throw(&self) -> bool {
+        matches!(self, Self::Throw { .. })
+    }
+
+    /// Wrap an arbitrary Rust error so it can cross the JS boundary without being flattened
+    /// to a string: `throw` raises a JS `Error` whose message is the wrapped error's
+    /// `Display`, while the original error and its `source()` chain remain reachable from
+    /// Rust through [`Error::source`] or by downcasting the value returned from
+    /// [`Error::as_wrapped`].
+    pub fn wrap<E>(error: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        Self::Wrapped(Arc::new(error))
+    }
+
+    /// Returns whether the error is a wrapped Rust error created with [`Error::wrap`].
+    pub fn is_wrapped(&self) -> bool {
+        matches!(self, Self::Wrapped(_))
+    }
+
+    /// Returns the wrapped Rust error, if this is an [`Error::Wrapped`], for downcasting
+    /// back to its original type.
+    pub fn as_wrapped(&self) -> Option<&(dyn StdError + Send + Sync + 'static)> {
+        match self {
+            Self::Wrapped(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+
     /// Optimized conversion to CString
     pub(crate) fn to_cstring(&self) -> CString {
         // stringify error with NUL at end
@@ -226,8 +607,37 @@ impl Error {
     /// Throw an exception
     pub(crate) fn throw(&self, ctx: Ctx) -> qjs::JSValue {
         use Error::*;
+
+        // Stash the original Rust error on the runtime before doing anything else, so the
+        // caller can recover it with `Ctx::take_wrapped_error` regardless of whether a
+        // `GetErrorClassFn` hook below ends up choosing the thrown class instead of the
+        // `Wrapped` arm. Requires `wrapped_error: Option<Arc<dyn StdError + Send + Sync>>` on
+        // the runtime opaque data, defaulted to `None`; that struct lives outside `result.rs`.
+        if let Wrapped(error) = self {
+            ctx.get_opaque().wrapped_error = Some(error.clone());
+        }
+
+        // Give a user-installed `GetErrorClassFn` hook first refusal on every error except an
+        // already-pending JS exception, which must keep unwinding as-is.
+        if !matches!(self, Exception(_)) {
+            if let Some(name) = ctx
+                .get_opaque()
+                .error_class_fn
+                .as_ref()
+                .and_then(|hook| hook(self))
+            {
+                let message = self.to_cstring();
+                if let Some(result) = unsafe { self.try_throw_by_class_name(ctx, name, &message) } {
+                    return result;
+                }
+                // `name` isn't one of the builtins and doesn't resolve to a global
+                // constructor either; fall back to the built-in mapping below instead of
+                // throwing on an undefined constructor.
+            }
+        }
+
         match self {
-            Exception => qjs::JS_EXCEPTION,
+            Exception(_) => qjs::JS_EXCEPTION,
             Allocation => unsafe { qjs::JS_ThrowOutOfMemory(ctx.as_ptr()) },
             InvalidString(_) | Utf8(_) | FromJs { .. } | IntoJs { .. } | NumArgs { .. } => {
                 let message = self.to_cstring();
@@ -242,30 +652,104 @@ impl Error {
                 let message = self.to_cstring();
                 unsafe { qjs::JS_ThrowInternalError(ctx.as_ptr(), message.as_ptr()) }
             }
-            error => {
-                unsafe {
-                    let value = qjs::JS_NewError(ctx.as_ptr());
-                    if qjs::JS_VALUE_GET_NORM_TAG(value) == qjs::JS_TAG_EXCEPTION {
-                        //allocation error happened, can't raise error properly. just immediately
-                        //return
-                        return value;
-                    }
-                    let obj = Object::from_js_value(ctx, value);
-                    match obj.set("message", error.to_string()) {
-                        Ok(_) => {}
-                        Err(Error::Exception) => return qjs::JS_EXCEPTION,
-                        Err(e) => {
-                            panic!("generated error while throwing error: {}", e);
-                        }
-                    }
-                    return qjs::JS_Throw(ctx.as_ptr(), obj.into_js_value());
-                }
+            Throw { class, message } => {
+                let message = CString::new(message.as_str())
+                    .unwrap_or_else(|_| CString::new("<error message contains null byte>").unwrap());
+                unsafe { self.throw_by_class_name(ctx, class.name(), &message) }
+            }
+            Wrapped(_) => unsafe { self.throw_generic(ctx) },
+            DuplicateExports | InvalidCStr(_) | UnrelatedRuntime | Io(_) | InvalidSourceMap(_) => unsafe {
+                self.throw_generic(ctx)
+            },
+        }
+    }
+
+    /// Construct a plain JS `Error` with `message` set to this error's `Display` and throw it.
+    /// Used for variants with no more specific JS error class to map to.
+    unsafe fn throw_generic(&self, ctx: Ctx) -> qjs::JSValue {
+        let value = qjs::JS_NewError(ctx.as_ptr());
+        if qjs::JS_VALUE_GET_NORM_TAG(value) == qjs::JS_TAG_EXCEPTION {
+            //allocation error happened, can't raise error properly. just immediately
+            //return
+            return value;
+        }
+        let obj = Object::from_js_value(ctx, value);
+        match obj.set("message", self.to_string()) {
+            Ok(_) => {}
+            Err(Error::Exception(_)) => return qjs::JS_EXCEPTION,
+            Err(e) => {
+                panic!("generated error while throwing error: {}", e);
             }
         }
+        qjs::JS_Throw(ctx.as_ptr(), obj.into_js_value())
+    }
+
+    /// Throw `message` as an instance of the JS error class named `name`, dispatching to the
+    /// matching `qjs::JS_Throw*Error` function for the builtins that have one and otherwise
+    /// looking `name` up as a global constructor, as [`Self::try_throw_named_class`] does.
+    /// Falls back to a plain `Error` if `name` doesn't resolve to any known or global class.
+    unsafe fn throw_by_class_name(&self, ctx: Ctx, name: &str, message: &CString) -> qjs::JSValue {
+        self.try_throw_by_class_name(ctx, name, message)
+            .unwrap_or_else(|| self.throw_generic(ctx))
+    }
+
+    /// Like [`Self::throw_by_class_name`], but returns `None` instead of falling back when
+    /// `name` isn't one of the builtins and doesn't resolve to a global constructor either, so
+    /// callers with their own fallback (the `GetErrorClassFn` hook) can use it instead.
+    unsafe fn try_throw_by_class_name(&self, ctx: Ctx, name: &str, message: &CString) -> Option<qjs::JSValue> {
+        // `JS_Throw*Error` are printf-style: `message` is read as a format string, so passing
+        // arbitrary user text (e.g. containing `%s`) directly would read nonexistent varargs.
+        // Always pass a literal `"%s"` format with `message` as its single argument.
+        const FORMAT: &CStr = c"%s";
+        Some(match name {
+            "TypeError" => qjs::JS_ThrowTypeError(ctx.as_ptr(), FORMAT.as_ptr(), message.as_ptr()),
+            "RangeError" => qjs::JS_ThrowRangeError(ctx.as_ptr(), FORMAT.as_ptr(), message.as_ptr()),
+            "ReferenceError" => qjs::JS_ThrowReferenceError(ctx.as_ptr(), FORMAT.as_ptr(), message.as_ptr()),
+            "SyntaxError" => qjs::JS_ThrowSyntaxError(ctx.as_ptr(), FORMAT.as_ptr(), message.as_ptr()),
+            "InternalError" => qjs::JS_ThrowInternalError(ctx.as_ptr(), FORMAT.as_ptr(), message.as_ptr()),
+            name => return self.try_throw_named_class(ctx, name, message),
+        })
+    }
+
+    /// Construct an instance of the named global error class with `message` and throw it.
+    /// Returns `None` if `name` contains an interior NUL byte, or isn't defined as a global (the
+    /// lookup returns `undefined`), so the caller can fall back to another mapping instead of
+    /// calling a constructor on `undefined`.
+    ///
+    /// Used for error classes without a dedicated `JS_Throw*Error` function, i.e. `Error`,
+    /// `EvalError`, `URIError`, and any user-defined class.
+    unsafe fn try_throw_named_class(&self, ctx: Ctx, name: &str, message: &CString) -> Option<qjs::JSValue> {
+        let name = CString::new(name).ok()?;
+        let global = qjs::JS_GetGlobalObject(ctx.as_ptr());
+        let ctor = qjs::JS_GetPropertyStr(ctx.as_ptr(), global, name.as_ptr());
+        qjs::JS_FreeValue(ctx.as_ptr(), global);
+        if qjs::JS_VALUE_GET_NORM_TAG(ctor) == qjs::JS_TAG_UNDEFINED {
+            qjs::JS_FreeValue(ctx.as_ptr(), ctor);
+            return None;
+        }
+        if qjs::JS_VALUE_GET_NORM_TAG(ctor) == qjs::JS_TAG_EXCEPTION {
+            return Some(ctor);
+        }
+        let message_val = qjs::JS_NewString(ctx.as_ptr(), message.as_ptr());
+        let mut args = [message_val];
+        let instance = qjs::JS_CallConstructor(ctx.as_ptr(), ctor, args.len() as i32, args.as_mut_ptr());
+        qjs::JS_FreeValue(ctx.as_ptr(), ctor);
+        qjs::JS_FreeValue(ctx.as_ptr(), message_val);
+        if qjs::JS_VALUE_GET_NORM_TAG(instance) == qjs::JS_TAG_EXCEPTION {
+            return Some(instance);
+        }
+        Some(qjs::JS_Throw(ctx.as_ptr(), instance))
     }
 }
 
-impl StdError for Error {}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Wrapped(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
@@ -289,7 +773,8 @@ impl Display for Error {
                 error.fmt(f)?;
             }
             Unknown => "quickjs library created a unknown error".fmt(f)?,
-            Exception => "quickjs generated an exception".fmt(f)?,
+            Exception(Some(e)) => e.fmt(f)?,
+            Exception(None) => "quickjs generated an exception".fmt(f)?,
             FromJs { from, to, message } => {
                 "Error converting from js '".fmt(f)?;
                 from.fmt(f)?;
@@ -362,6 +847,18 @@ impl Display for Error {
                 error.fmt(f)?;
             }
             UnrelatedRuntime => "Restoring Persistent in an unrelated runtime".fmt(f)?,
+            Throw { class, message } => {
+                class.name().fmt(f)?;
+                if !message.is_empty() {
+                    ": ".fmt(f)?;
+                    message.fmt(f)?;
+                }
+            }
+            InvalidSourceMap(message) => {
+                "Invalid source map: ".fmt(f)?;
+                message.fmt(f)?;
+            }
+            Wrapped(error) => error.fmt(f)?,
         }
         Ok(())
     }
@@ -420,7 +917,7 @@ impl<'js> Ctx<'js> {
             if let Some(x) = self.get_opaque().panic.take() {
                 panic::resume_unwind(x)
             }
-            Err(Error::Exception)
+            Err(Error::Exception(self.capture_exception()))
         }
     }
 
@@ -430,6 +927,261 @@ impl<'js> Ctx<'js> {
         if let Some(x) = self.get_opaque().panic.take() {
             panic::resume_unwind(x)
         }
-        Error::Exception
+        Error::Exception(self.capture_exception())
+    }
+
+    /// Reads the `name`, `message`, and `stack` of the pending exception into a
+    /// [`JsException`], then puts the exception back as pending so that callers which only
+    /// care about unwinding (i.e. `Error::throw` on `Error::Exception`) keep working unchanged.
+    unsafe fn capture_exception(self) -> Option<Box<JsException>> {
+        let exc = qjs::JS_GetException(self.as_ptr());
+
+        // JS permits throwing any value, not just `Error` instances (`throw "oops"`,
+        // `throw 42`). Only objects carry `name`/`message`/`stack`, so leave non-objects at
+        // their defaults rather than reading properties off them.
+        let exception = if is_object_exception(exc) {
+            let obj = Object::from_js_value(self, exc);
+
+            let name: StdString = obj.get("name").unwrap_or_else(|_| "Error".into());
+            let message: StdString = obj.get("message").unwrap_or_default();
+            let stack: Option<StdString> = obj
+                .get("stack")
+                .ok()
+                .filter(|s: &StdString| !s.is_empty())
+                .map(|s: StdString| self.rewrite_stack(&s));
+            let (file, line) = stack
+                .as_deref()
+                .and_then(parse_top_frame)
+                .map_or((None, None), |(f, l)| (Some(f), Some(l)));
+
+            qjs::JS_Throw(self.as_ptr(), obj.into_js_value());
+
+            JsException { name, message, stack, file, line }
+        } else {
+            qjs::JS_Throw(self.as_ptr(), exc);
+            JsException { name: "Error".into(), ..Default::default() }
+        };
+
+        Some(Box::new(exception))
+    }
+
+    /// Rewrite every frame of `stack` whose file has a source map registered via
+    /// [`Ctx::register_source_map`] back to its original position.
+    unsafe fn rewrite_stack(self, stack: &str) -> StdString {
+        rewrite_stack_with(&self.get_opaque().source_maps, stack)
+    }
+
+    /// Register a source map for `url`, used to rewrite the `stack` of exceptions thrown
+    /// from that script back to original source positions.
+    ///
+    /// Requires `source_maps: HashMap<StdString, SourceMap>` on the runtime opaque data,
+    /// defaulted to empty; that struct lives outside `result.rs` and isn't part of this change.
+    pub fn register_source_map<N>(self, url: N, json: &str) -> Result<()>
+    where
+        StdString: From<N>,
+    {
+        let map = SourceMap::parse(json)
+            .ok_or_else(|| Error::InvalidSourceMap("missing or invalid `mappings`".into()))?;
+        self.get_opaque().source_maps.insert(url.into(), map);
+        Ok(())
+    }
+
+    /// Take the Rust error most recently wrapped with [`Error::wrap`] and thrown into JS, if
+    /// any. Call this after catching the JS exception it raised to recover the original error,
+    /// e.g. `ctx.take_wrapped_error().and_then(|e| e.downcast_ref::<MyError>().cloned())`.
+    pub fn take_wrapped_error(self) -> Option<Arc<dyn StdError + Send + Sync + 'static>> {
+        self.get_opaque().wrapped_error.take()
+    }
+}
+
+/// A stack frame split into the text preceding its location and the parsed 1-based generated
+/// position. See [`split_frame_location`].
+struct FrameLocation<'a> {
+    head: &'a str,
+    file: &'a str,
+    gen_line: u32,
+    /// `None` for the column-less `file:line` form some quickjs builds emit.
+    gen_col: Option<u32>,
+    /// Whether the original frame was wrapped in parens, e.g. `"(bundle.js:12:5)"`.
+    had_paren: bool,
+}
+
+/// Split a stack frame into the text preceding its location and its generated position, e.g.
+/// `"    at foo (bundle.js:12:5)"` -> `head: "    at foo (", file: "bundle.js", gen_line: 12,
+/// gen_col: Some(5), had_paren: true`. Also accepts the parenthesis-less form
+/// `"    at bundle.js:12:5"` and the column-less form `"    at bundle.js:12"`.
+fn split_frame_location(line: &str) -> Option<FrameLocation<'_>> {
+    let had_paren = line.trim_end().ends_with(')');
+    let trimmed = line.trim_end_matches(')');
+    let (head, location) = match trimmed.rfind('(') {
+        Some(index) => trimmed.split_at(index + 1),
+        None => trimmed.split_at(trimmed.find("at ")? + "at ".len()),
+    };
+    let mut parts = location.rsplitn(3, ':');
+    let first = parts.next()?;
+    let second = parts.next()?;
+    match parts.next() {
+        Some(file) => Some(FrameLocation {
+            head,
+            file,
+            gen_line: second.parse().ok()?,
+            gen_col: Some(first.parse().ok()?),
+            had_paren,
+        }),
+        None => Some(FrameLocation {
+            head,
+            file: second,
+            gen_line: first.parse().ok()?,
+            gen_col: None,
+            had_paren,
+        }),
+    }
+}
+
+/// Rewrite every frame of `stack` whose file has a registered source map back to its original
+/// position. Pure function underlying [`Ctx::rewrite_stack`], kept free of `Ctx` for testing.
+fn rewrite_stack_with(source_maps: &HashMap<StdString, SourceMap>, stack: &str) -> StdString {
+    if source_maps.is_empty() {
+        return stack.to_string();
+    }
+    stack
+        .lines()
+        .map(|line| rewrite_frame_with(source_maps, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Rewrite a single stack frame, returning it unchanged if it doesn't match the expected shape
+/// or its file has no registered map. Used by [`rewrite_stack_with`] per line.
+fn rewrite_frame_with(source_maps: &HashMap<StdString, SourceMap>, line: &str) -> StdString {
+    let Some(loc) = split_frame_location(line) else {
+        return line.to_string();
+    };
+    let Some(map) = source_maps.get(loc.file) else {
+        return line.to_string();
+    };
+    let Some(gen_line) = loc.gen_line.checked_sub(1) else {
+        return line.to_string();
+    };
+    let resolved = match loc.gen_col {
+        Some(gen_col) => gen_col.checked_sub(1).and_then(|gen_col| map.resolve(gen_line, gen_col)),
+        None => map.resolve_line(gen_line),
+    };
+    let Some((src_file, src_line, src_col)) = resolved else {
+        return line.to_string();
+    };
+    let paren = if loc.had_paren { ")" } else { "" };
+    format!("{}{src_file}:{}:{}{paren}", loc.head, src_line + 1, src_col + 1)
+}
+
+/// Parse the file and line number out of the topmost frame of a quickjs stack trace, e.g.
+/// `"    at foo (file.js:12:5)\n..."` or `"    at file.js:12:5\n..."`.
+fn parse_top_frame(stack: &str) -> Option<(StdString, u32)> {
+    let loc = split_frame_location(stack.lines().next()?)?;
+    Some((loc.file.to_string(), loc.gen_line))
+}
+
+/// Whether a thrown value is an object (and so might carry `name`/`message`/`stack`), as
+/// opposed to a primitive like a string or number thrown directly, e.g. `throw "oops"`.
+fn is_object_exception(value: qjs::JSValue) -> bool {
+    qjs::JS_VALUE_GET_NORM_TAG(value) == qjs::JS_TAG_OBJECT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_object_exception_values_are_not_treated_as_objects() {
+        // `throw 42` and `throw "oops"` both land on a primitive JSValue; capture_exception
+        // must not try to read `name`/`message`/`stack` off them as it would for a thrown
+        // `Error` instance.
+        let thrown_number = qjs::JS_MKVAL(qjs::JS_TAG_INT, 42);
+        assert!(!is_object_exception(thrown_number));
+    }
+
+    #[test]
+    fn vlq_decodes_single_char_values() {
+        assert_eq!(base64_vlq_decode("AAAA"), vec![0, 0, 0, 0]);
+        assert_eq!(base64_vlq_decode("D"), vec![-1]);
+    }
+
+    #[test]
+    fn vlq_decodes_continuation_values() {
+        assert_eq!(base64_vlq_decode("gB"), vec![16]);
+    }
+
+    fn sample_map() -> SourceMap {
+        let mut lines = vec![Vec::new(); 11];
+        lines.push(vec![Segment {
+            gen_col: 4,
+            src_idx: 0,
+            src_line: 9,
+            src_col: 2,
+        }]);
+        SourceMap {
+            sources: vec!["orig.ts".to_string()],
+            lines,
+        }
+    }
+
+    #[test]
+    fn parse_mappings_decodes_deltas_across_lines() {
+        let lines = parse_mappings("AAAA;AACA");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), 1);
+        assert_eq!(lines[0][0].src_line, 0);
+        assert_eq!(lines[1].len(), 1);
+        assert_eq!(lines[1][0].src_line, 1);
+    }
+
+    #[test]
+    fn source_map_resolve_finds_nearest_segment() {
+        let json = r#"{"version":3,"sources":["a.ts"],"names":[],"mappings":"AAAA;AACA"}"#;
+        let map = SourceMap::parse(json).unwrap();
+        assert_eq!(map.resolve(0, 0), Some(("a.ts", 0, 0)));
+        // Col 5 has no exact segment on line 1; falls back to the nearest preceding one.
+        assert_eq!(map.resolve(1, 5), Some(("a.ts", 1, 0)));
+        assert_eq!(map.resolve(5, 0), None);
+    }
+
+    #[test]
+    fn split_frame_location_parses_parenthesized_frame() {
+        let loc = split_frame_location("    at foo (bundle.js:12:5)").unwrap();
+        assert_eq!(loc.head, "    at foo (");
+        assert_eq!(loc.file, "bundle.js");
+        assert_eq!(loc.gen_line, 12);
+        assert_eq!(loc.gen_col, Some(5));
+        assert!(loc.had_paren);
+    }
+
+    #[test]
+    fn split_frame_location_parses_bare_frame_without_column() {
+        let loc = split_frame_location("    at bundle.js:12").unwrap();
+        assert_eq!(loc.head, "    at ");
+        assert_eq!(loc.file, "bundle.js");
+        assert_eq!(loc.gen_line, 12);
+        assert_eq!(loc.gen_col, None);
+        assert!(!loc.had_paren);
+    }
+
+    #[test]
+    fn rewrite_stack_rewrites_frames_with_registered_maps_and_preserves_parens() {
+        let mut maps = HashMap::new();
+        maps.insert("bundle.js".to_string(), sample_map());
+        let stack = "    at foo (bundle.js:12:5)\n    at bar (other.js:3:1)";
+        let rewritten = rewrite_stack_with(&maps, stack);
+        assert_eq!(
+            rewritten,
+            "    at foo (orig.ts:10:3)\n    at bar (other.js:3:1)"
+        );
+    }
+
+    #[test]
+    fn rewrite_frame_falls_back_to_line_only_resolution_without_column() {
+        let mut maps = HashMap::new();
+        maps.insert("bundle.js".to_string(), sample_map());
+        let rewritten = rewrite_frame_with(&maps, "    at bundle.js:12");
+        assert_eq!(rewritten, "    at orig.ts:10:3");
     }
 }